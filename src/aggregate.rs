@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use csv::Writer;
+
+use crate::csvio::read_ragged_columns;
+use crate::lag::{compute_bins, estimate_lag, Bin};
+
+/// Parse a device's ragged time/x/y/z columns (skipping the header cell) and
+/// the magnitude sequence derived from x/y/z, used both for lag estimation
+/// and for reporting.
+fn parse_column(csv: &[Vec<String>], column_i5th: usize) -> (Vec<f64>, Vec<f64>) {
+    let base = column_i5th * 5;
+    let times: Vec<f64> = csv[base][1..]
+        .iter()
+        .map(|v| str::parse(v).unwrap())
+        .collect();
+    let mag: Vec<f64> = (1..csv[base].len())
+        .map(|row_i| {
+            let x: f64 = str::parse(&csv[base + 1][row_i]).unwrap();
+            let y: f64 = str::parse(&csv[base + 2][row_i]).unwrap();
+            let z: f64 = str::parse(&csv[base + 3][row_i]).unwrap();
+            (x * x + y * y + z * z).sqrt()
+        })
+        .collect();
+    (times, mag)
+}
+
+/// Extract `(name, num)` from a device's "X" header cell, e.g.
+/// `"front left: X (3)"` -> `("front left", "3")`.
+fn parse_device_label(header_cell: &str) -> Option<(&str, &str)> {
+    let (name, rest) = header_cell.split_once(": X (")?;
+    let num = rest.strip_suffix(')')?;
+    Some((name, num))
+}
+
+/// Estimate, for every non-reference device, the lag (in seconds) that best
+/// aligns its signal to the reference device's, and write a sidecar report
+/// of the chosen lags so users can see what was applied. `bins` is the
+/// reference device's own binning, shared with the aggregation loop below so
+/// the half-midpoint bounds are only ever computed once.
+fn estimate_lags(csv: &[Vec<String>], reference_i5th: usize, bins: &[Bin]) -> Vec<f64> {
+    let device_count = csv.len() / 5;
+    let (_, reference_mag) = parse_column(csv, reference_i5th);
+
+    let mut lags = vec![0.0; device_count];
+
+    let file = File::create("output_lags.csv").expect("Failed to create lag report");
+    let mut report = Writer::from_writer(BufWriter::new(file));
+    report
+        .write_record(["device", "num", "lag_s", "correlation"])
+        .expect("Failed to write lag report header");
+
+    for column_i5th in 0..device_count {
+        let (name, num) = parse_device_label(&csv[column_i5th * 5 + 1][0]).unwrap_or(("?", "?"));
+        if column_i5th == reference_i5th {
+            report
+                .write_record([name, num, "0", "1"])
+                .expect("Failed to write lag report row");
+            continue;
+        }
+
+        let (sensor_times, sensor_mag) = parse_column(csv, column_i5th);
+        let estimate = estimate_lag(bins, &reference_mag, &sensor_times, &sensor_mag);
+        lags[column_i5th] = estimate.lag;
+        report
+            .write_record([
+                name,
+                num,
+                &estimate.lag.to_string(),
+                &estimate.correlation.to_string(),
+            ])
+            .expect("Failed to write lag report row");
+    }
+
+    report.flush().expect("Failed to flush lag report");
+    lags
+}
+
+pub fn aggregate() {
+    let csv = read_ragged_columns("output.csv");
+
+    //do the actual aggregation
+    //find the smallest time range
+    let mut smallest_time_range =
+        str::parse::<f64>(&csv[0][csv[0].len() - 1]).unwrap() - str::parse::<f64>(&csv[0][1]).unwrap();
+    let mut smallest_time_range_i5th = 0;
+    for column_i5th in 1..csv.len() / 5 {
+        let column_i = column_i5th * 5;
+        let range = str::parse::<f64>(&csv[column_i][csv[column_i].len() - 1]).unwrap()
+            - str::parse::<f64>(&csv[column_i][1]).unwrap();
+        if range < smallest_time_range {
+            smallest_time_range_i5th = column_i5th;
+            smallest_time_range = range;
+        }
+    }
+
+    // Bins derived once from the reference device's own timestamps; shared
+    // between lag estimation and the aggregation loop below so the
+    // half-midpoint bounds are computed in exactly one place.
+    let reference_times: Vec<f64> = csv[smallest_time_range_i5th * 5][1..]
+        .iter()
+        .map(|v| str::parse(v).unwrap())
+        .collect();
+    let bins = compute_bins(&reference_times);
+
+    // align every other device's clock to the reference before binning
+    let lags = estimate_lags(&csv, smallest_time_range_i5th, &bins);
+
+    let mut target_csv: Vec<Vec<f64>> = (0..csv.len() / 5 * 3 + 1)
+        .map(|_| (0..bins.len()).map(|_| 0.0).collect())
+        .collect();
+    //iterate through the reference device's bins
+    for (row_target, bin) in bins.iter().enumerate() {
+        target_csv[0][row_target] = bin.target;
+
+        for column_i5th in 0..csv.len() / 5 {
+            let mut sum_x = 0.0;
+            let mut sum_y = 0.0;
+            let mut sum_z = 0.0;
+            let mut counted = 0;
+
+            for row_i in 1..csv[column_i5th * 5].len() {
+                let timestamp: f64 =
+                    str::parse::<f64>(&csv[column_i5th * 5][row_i]).unwrap() + lags[column_i5th];
+                if timestamp == bin.target || (timestamp >= bin.lower && timestamp < bin.upper) {
+                    sum_x += str::parse::<f64>(&csv[column_i5th * 5 + 1][row_i]).unwrap();
+                    sum_y += str::parse::<f64>(&csv[column_i5th * 5 + 2][row_i]).unwrap();
+                    sum_z += str::parse::<f64>(&csv[column_i5th * 5 + 3][row_i]).unwrap();
+                    counted += 1;
+                } else if timestamp > bin.upper {
+                    break;
+                }
+            }
+
+            if counted == 0 {
+                if row_target > 1 {
+                    target_csv[column_i5th * 3 + 1][row_target] =
+                        target_csv[column_i5th * 3 + 1][row_target - 1];
+                    target_csv[column_i5th * 3 + 2][row_target] =
+                        target_csv[column_i5th * 3 + 2][row_target - 1];
+                    target_csv[column_i5th * 3 + 3][row_target] =
+                        target_csv[column_i5th * 3 + 3][row_target - 1];
+                }
+                continue;
+            }
+
+            let average_x = sum_x / counted as f64;
+            let average_y = sum_y / counted as f64;
+            let average_z = sum_z / counted as f64;
+            target_csv[column_i5th * 3 + 1][row_target] = average_x;
+            target_csv[column_i5th * 3 + 2][row_target] = average_y;
+            target_csv[column_i5th * 3 + 3][row_target] = average_z;
+        }
+    }
+
+    let mut header = vec!["Time (s)".to_string()];
+    for column_i5th in 0..csv.len() / 5 {
+        for offset in 1..4 {
+            header.push(csv[column_i5th * 5 + offset][0].clone());
+        }
+    }
+
+    let file = File::create("output_aggregated.csv").expect("Failed to create output file");
+    let mut out = Writer::from_writer(BufWriter::new(file));
+    out.write_record(&header)
+        .expect("Failed to write aggregated header");
+    for row in 0..target_csv[0].len() {
+        let record: Vec<String> = (0..target_csv.len())
+            .map(|column| target_csv[column][row].to_string())
+            .collect();
+        out.write_record(&record)
+            .expect("Failed to write aggregated row");
+    }
+    out.flush().expect("Failed to flush aggregated output");
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+};
+
+/// Tees every received datagram to disk, length-prefixed and tagged with the
+/// server's receive time, so a session can be replayed offline later.
+pub struct CaptureWriter {
+    file: File,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+        })
+    }
+
+    pub fn write_datagram(&mut self, recv_time: f64, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(&recv_time.to_le_bytes())?;
+        self.file.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.file.write_all(data)?;
+        Ok(())
+    }
+}
+
+/// Reads records written by `CaptureWriter` back out in order.
+pub struct CaptureReader {
+    file: File,
+}
+
+impl CaptureReader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+        })
+    }
+
+    /// Returns the next `(receive_time, raw_datagram)` record, or `None` at EOF.
+    pub fn next_record(&mut self) -> io::Result<Option<(f64, Vec<u8>)>> {
+        let mut time_buf = [0u8; 8];
+        match self.file.read_exact(&mut time_buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let recv_time = f64::from_le_bytes(time_buf);
+
+        let mut len_buf = [0u8; 4];
+        self.file.read_exact(&mut len_buf)?;
+        let len = u32::from_le_bytes(len_buf) as usize;
+
+        let mut data = vec![0u8; len];
+        self.file.read_exact(&mut data)?;
+
+        Ok(Some((recv_time, data)))
+    }
+}
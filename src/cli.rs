@@ -0,0 +1,61 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "udp-csv-server")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Listen for sensor datagrams and write them to a CSV file.
+    Serve {
+        /// Socket address to bind the UDP listener to.
+        #[arg(long)]
+        bind: Option<String>,
+        /// YAML config file with the device map and server options. Falls
+        /// back to the interactive stdin prompt when omitted.
+        #[arg(long)]
+        config: Option<String>,
+        /// Gzip-compress the output file.
+        #[arg(long)]
+        gzip: bool,
+        /// Also tee every raw datagram to this file for later `replay`.
+        #[arg(long)]
+        capture: Option<String>,
+        /// Path to write the CSV output to.
+        #[arg(long)]
+        output: Option<String>,
+        /// Keep at most this many samples per device in memory (mutually
+        /// exclusive with `--retention-secs`).
+        #[arg(long, conflicts_with = "retention_secs")]
+        retention_samples: Option<usize>,
+        /// Keep at most this many seconds of samples per device in memory
+        /// (mutually exclusive with `--retention-samples`).
+        #[arg(long)]
+        retention_secs: Option<f64>,
+    },
+    /// Bin a saved CSV by the reference device's timestamps.
+    Aggregate,
+    /// Re-feed a capture file through the same parsing/CSV pipeline as a live run.
+    Replay {
+        /// Capture file written by `serve --capture`.
+        file: String,
+    },
+    /// Filter a saved CSV down to a time window.
+    Range {
+        /// Start of the window, as an RFC3339 timestamp or seconds since the epoch.
+        #[arg(long)]
+        start: String,
+        /// End of the window, as an RFC3339 timestamp or seconds since the epoch.
+        #[arg(long)]
+        end: String,
+        /// CSV file to read from.
+        #[arg(short = 'f', long = "file")]
+        file: String,
+        /// CSV file to write the filtered rows to.
+        #[arg(short = 'o', long = "output")]
+        output: String,
+    },
+}
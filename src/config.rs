@@ -0,0 +1,29 @@
+use std::{collections::HashMap, fs};
+
+use serde::Deserialize;
+
+/// Optional config file loaded with `serve --config`, covering everything
+/// the interactive stdin prompt used to ask for plus the options `--gzip`/
+/// `--capture`/`--output` otherwise take on the command line. Command-line
+/// flags, when given, take priority over the matching config value.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub devices: HashMap<u8, String>,
+    #[serde(default)]
+    pub bind: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub gzip: Option<bool>,
+    #[serde(default)]
+    pub capture: Option<String>,
+    #[serde(default)]
+    pub retention_samples: Option<usize>,
+    #[serde(default)]
+    pub retention_secs: Option<f64>,
+}
+
+pub fn load_config(path: &str) -> Config {
+    let contents = fs::read_to_string(path).expect("Failed to read config file");
+    serde_yaml::from_str(&contents).expect("Failed to parse config file")
+}
@@ -0,0 +1,54 @@
+//! Shared CSV helpers for the ragged per-device columnar layout used by
+//! `aggregate` and `range`. Centralized here so both go through the `csv`
+//! crate instead of each hand-rolling `split(',')`/byte-pushing, which broke
+//! on quoted fields and device names containing commas.
+use std::fs::File;
+use std::io::BufWriter;
+
+use csv::{ReaderBuilder, WriterBuilder};
+
+/// Read a CSV file into its columns rather than its rows, skipping blank
+/// cells. Device blocks are ragged (a device's column only has entries for
+/// the rows it reported in), so this preserves that shape instead of forcing
+/// every column to the same length.
+pub fn read_ragged_columns(path: &str) -> Vec<Vec<String>> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .expect("Failed to open CSV file");
+
+    let mut columns: Vec<Vec<String>> = Vec::new();
+    for result in reader.records() {
+        let record = result.expect("Failed to parse CSV row");
+        if record.len() > columns.len() {
+            columns.resize(record.len(), Vec::new());
+        }
+        for (column_i, field) in record.iter().enumerate() {
+            if field.is_empty() {
+                continue;
+            }
+            columns[column_i].push(field.to_string());
+        }
+    }
+    columns
+}
+
+/// Write ragged columns back out as rows, padding short columns with blank
+/// cells, the inverse of `read_ragged_columns`.
+pub fn write_columns(columns: &[Vec<String>], path: &str) {
+    let file = File::create(path).expect("Failed to create output file");
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(BufWriter::new(file));
+
+    let max_rows = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+    for row in 0..max_rows {
+        let record: Vec<&str> = columns
+            .iter()
+            .map(|column| column.get(row).map(String::as_str).unwrap_or(""))
+            .collect();
+        writer.write_record(&record).expect("Failed to write row");
+    }
+    writer.flush().expect("Failed to flush output file");
+}
@@ -0,0 +1,36 @@
+use std::{collections::HashMap, io};
+
+/// device number -> (display name, first column of its 5-wide block)
+pub type DeviceMap = HashMap<u8, (String, usize)>;
+
+fn assign_columns(entries: HashMap<u8, String>) -> DeviceMap {
+    let mut device_map = HashMap::new();
+    let mut column = 0;
+    for (num, name) in entries {
+        device_map.insert(num, (name, column));
+        column += 5;
+    }
+    device_map
+}
+
+/// Brittle interactive fallback used when `serve` isn't given `--config`.
+pub fn read_device_map_from_stdin() -> DeviceMap {
+    println!("Enter the devices in the following format: <device #>:<device name>,:");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+
+    let mut entries = HashMap::new();
+    line.split(',').for_each(|part| {
+        let subparts: Vec<&str> = part.split(':').collect();
+        let num: u8 = str::parse(subparts.get(0).expect("Invalid formatting: not enough parts between commas (do not use a trailing comma)").trim()).expect(&format!("Invalid formatting: {} is not an integer in [0,255]", subparts[0]));
+        let name = subparts.get(1).expect(&format!("Invalid formatting: name not supplied for device number {}", num)).trim();
+        entries.insert(num, name.to_string());
+    });
+
+    assign_columns(entries)
+}
+
+/// Build a device map from a config file's `devices` table.
+pub fn device_map_from_config(entries: &HashMap<u8, String>) -> DeviceMap {
+    assign_columns(entries.clone())
+}
@@ -0,0 +1,247 @@
+//! Cross-correlation based lag estimation, used by `aggregate` to align
+//! independent sensors that don't share a clock before binning them against
+//! the reference device.
+
+/// Smallest candidate offset magnitude: tens of milliseconds.
+const LOGSPACE_MIN_SECS: f64 = 0.02;
+/// Largest candidate offset magnitude: a few seconds.
+const LOGSPACE_MAX_SECS: f64 = 3.0;
+/// Number of candidates per sign; together with the mirrored negative half
+/// and the zero-lag candidate this gives ~128 offsets, densest near zero.
+const LOGSPACE_POINTS_PER_SIDE: usize = 64;
+
+/// A skip sensor has too few overlapping samples with the reference to trust
+/// a correlation estimate at all.
+const MIN_OVERLAP_SAMPLES: usize = 5;
+/// Below this correlation we don't trust the best candidate lag and fall
+/// back to no shift.
+const MIN_CORRELATION: f64 = 0.3;
+
+/// One bin of the reference device's own timeline: `target` is the
+/// reference sample's timestamp, and `[lower, upper)` is the half-midpoint
+/// window around it that other devices' samples get resampled into.
+pub struct Bin {
+    pub lower: f64,
+    pub upper: f64,
+    pub target: f64,
+}
+
+pub struct LagEstimate {
+    pub lag: f64,
+    pub correlation: f64,
+}
+
+/// Build the reference device's bins from its own (sorted) timestamps, using
+/// the same half-midpoint convention `aggregate`'s binning loop uses.
+pub fn compute_bins(times: &[f64]) -> Vec<Bin> {
+    let n = times.len();
+    let mut bins = Vec::with_capacity(n);
+    for i in 0..n {
+        let target = times[i];
+        let prev = if i > 0 { times[i - 1] } else { target };
+        let next = if i + 1 < n { times[i + 1] } else { target };
+        bins.push(Bin {
+            lower: (prev + target) / 2.0,
+            upper: (next + target) / 2.0,
+            target,
+        });
+    }
+    bins
+}
+
+fn logspace_lags() -> Vec<f64> {
+    let mut lags = Vec::with_capacity(LOGSPACE_POINTS_PER_SIDE * 2 + 1);
+    lags.push(0.0);
+    for i in 0..LOGSPACE_POINTS_PER_SIDE {
+        let t = i as f64 / (LOGSPACE_POINTS_PER_SIDE - 1) as f64;
+        let mag = LOGSPACE_MIN_SECS * (LOGSPACE_MAX_SECS / LOGSPACE_MIN_SECS).powf(t);
+        lags.push(mag);
+        lags.push(-mag);
+    }
+    lags.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    lags
+}
+
+/// Average `mags` into each bin after shifting `times` by `lag`; `None` where
+/// no sample of the shifted signal falls in a bin.
+fn resample(times: &[f64], mags: &[f64], lag: f64, bins: &[Bin]) -> Vec<Option<f64>> {
+    bins.iter()
+        .map(|bin| {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for (&t, &m) in times.iter().zip(mags.iter()) {
+                let shifted = t + lag;
+                if shifted == bin.target || (shifted >= bin.lower && shifted < bin.upper) {
+                    sum += m;
+                    count += 1;
+                } else if shifted > bin.upper {
+                    break;
+                }
+            }
+            if count == 0 {
+                None
+            } else {
+                Some(sum / count as f64)
+            }
+        })
+        .collect()
+}
+
+fn pearson(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    for i in 0..xs.len() {
+        let dx = xs[i] - mean_x;
+        let dy = ys[i] - mean_y;
+        cov += dx * dy;
+        var_x += dx * dx;
+        var_y += dy * dy;
+    }
+
+    if var_x <= 0.0 || var_y <= 0.0 {
+        0.0
+    } else {
+        cov / (var_x.sqrt() * var_y.sqrt())
+    }
+}
+
+/// Fit a parabola through three (possibly unevenly spaced) points and return
+/// the x-coordinate of its vertex, for sub-grid refinement of the best lag.
+fn parabolic_vertex(x0: f64, y0: f64, x1: f64, y1: f64, x2: f64, y2: f64) -> Option<f64> {
+    let denom = (x0 - x1) * (x0 - x2) * (x1 - x2);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let a = (x2 * (y1 - y0) + x1 * (y0 - y2) + x0 * (y2 - y1)) / denom;
+    let b = (x2 * x2 * (y0 - y1) + x1 * x1 * (y2 - y0) + x0 * x0 * (y1 - y2)) / denom;
+    if a.abs() < 1e-12 {
+        return None;
+    }
+    Some(-b / (2.0 * a))
+}
+
+/// Find the lag that best aligns `sensor`'s magnitude sequence to the
+/// reference's, searching the log-spaced candidate grid and refining around
+/// the best grid point. Falls back to zero lag when there isn't enough
+/// overlap or the best correlation is too weak to trust.
+pub fn estimate_lag(
+    bins: &[Bin],
+    ref_mag: &[f64],
+    sensor_times: &[f64],
+    sensor_mag: &[f64],
+) -> LagEstimate {
+    let candidates = logspace_lags();
+    let mut correlations = vec![f64::NEG_INFINITY; candidates.len()];
+    let mut best_i = None;
+
+    for (i, &lag) in candidates.iter().enumerate() {
+        let resampled = resample(sensor_times, sensor_mag, lag, bins);
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        for (r, s) in ref_mag.iter().zip(resampled.iter()) {
+            if let Some(s) = s {
+                xs.push(*r);
+                ys.push(*s);
+            }
+        }
+        if xs.len() < MIN_OVERLAP_SAMPLES {
+            continue;
+        }
+
+        let corr = pearson(&xs, &ys);
+        correlations[i] = corr;
+        if best_i.is_none_or(|b| corr > correlations[b]) {
+            best_i = Some(i);
+        }
+    }
+
+    let Some(best_i) = best_i else {
+        return LagEstimate {
+            lag: 0.0,
+            correlation: 0.0,
+        };
+    };
+    let best_correlation = correlations[best_i];
+    if best_correlation < MIN_CORRELATION {
+        return LagEstimate {
+            lag: 0.0,
+            correlation: best_correlation,
+        };
+    }
+
+    let mut lag = candidates[best_i];
+    if best_i > 0 && best_i + 1 < candidates.len() {
+        let (x0, y0) = (candidates[best_i - 1], correlations[best_i - 1]);
+        let (x1, y1) = (candidates[best_i], correlations[best_i]);
+        let (x2, y2) = (candidates[best_i + 1], correlations[best_i + 1]);
+        if y0.is_finite() && y2.is_finite() {
+            if let Some(refined) = parabolic_vertex(x0, y0, x1, y1, x2, y2) {
+                if refined > x0 && refined < x2 {
+                    lag = refined;
+                }
+            }
+        }
+    }
+
+    LagEstimate {
+        lag,
+        correlation: best_correlation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single bump centered at `center`, not periodic, so there's exactly
+    /// one lag that maximizes correlation (a sine would alias across its
+    /// other periods and make the recovered lag ambiguous).
+    fn pulse_series(times: &[f64], center: f64, sigma: f64) -> Vec<f64> {
+        times
+            .iter()
+            .map(|t| (-(t - center).powi(2) / (2.0 * sigma * sigma)).exp())
+            .collect()
+    }
+
+    #[test]
+    fn estimate_lag_recovers_a_known_shift() {
+        let dt = 0.05;
+        let times: Vec<f64> = (0..100).map(|i| i as f64 * dt).collect();
+        let ref_mag = pulse_series(&times, 2.5, 0.3);
+        let bins = compute_bins(&times);
+
+        // The sensor's own clock reads 0.3s behind the reference, so its
+        // reported timestamps need a +0.3s shift to line back up.
+        let true_lag = 0.3;
+        let sensor_times: Vec<f64> = times.iter().map(|t| t - true_lag).collect();
+        let sensor_mag = pulse_series(&times, 2.5, 0.3);
+
+        let estimate = estimate_lag(&bins, &ref_mag, &sensor_times, &sensor_mag);
+
+        assert!(
+            (estimate.lag - true_lag).abs() < 0.05,
+            "expected lag near {true_lag}, got {}",
+            estimate.lag
+        );
+        assert!(estimate.correlation > 0.99);
+    }
+
+    #[test]
+    fn estimate_lag_falls_back_to_zero_with_too_few_overlapping_samples() {
+        let times = vec![0.0, 1.0];
+        let bins = compute_bins(&times);
+        let ref_mag = vec![1.0, 2.0];
+        let sensor_times = vec![0.0, 1.0];
+        let sensor_mag = vec![1.0, 2.0];
+
+        let estimate = estimate_lag(&bins, &ref_mag, &sensor_times, &sensor_mag);
+
+        assert_eq!(estimate.lag, 0.0);
+        assert_eq!(estimate.correlation, 0.0);
+    }
+}
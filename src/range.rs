@@ -0,0 +1,92 @@
+use chrono::DateTime;
+
+use crate::csvio::{read_ragged_columns, write_columns};
+
+/// Parse a `--start`/`--end` argument given either as an RFC3339 timestamp
+/// or as raw seconds since the Unix epoch (matching the `timestamp`/
+/// `recv_time` columns already in the CSV).
+fn parse_time_arg(s: &str) -> f64 {
+    if let Ok(secs) = s.parse::<f64>() {
+        return secs;
+    }
+    let dt = DateTime::parse_from_rfc3339(s)
+        .expect("--start/--end must be RFC3339 or seconds since the Unix epoch");
+    dt.timestamp() as f64 + dt.timestamp_subsec_nanos() as f64 / 1e9
+}
+
+/// Binary search `times` (assumed time-sorted, as each device's own column
+/// already is) for the half-open index range `[lo, hi)` of the times that
+/// fall in the closed window `[start, end]`.
+fn bounds(times: &[f64], start: f64, end: f64) -> (usize, usize) {
+    let lo = times.partition_point(|&t| t < start);
+    let hi = times.partition_point(|&t| t <= end);
+    (lo, hi)
+}
+
+/// Load a previously saved CSV and write only the rows whose device
+/// timestamp falls in `[start, end]` to `output_path`, per device.
+pub fn range(start: &str, end: &str, input_path: &str, output_path: &str) {
+    let start = parse_time_arg(start);
+    let end = parse_time_arg(end);
+
+    let csv = read_ragged_columns(input_path);
+
+    let mut out_columns: Vec<Vec<String>> = Vec::with_capacity(csv.len());
+    for column_i5th in 0..csv.len() / 5 {
+        let base = column_i5th * 5;
+        let times: Vec<f64> = csv[base][1..]
+            .iter()
+            .map(|v| str::parse(v).unwrap())
+            .collect();
+        let (lo, hi) = bounds(&times, start, end);
+
+        for column in &csv[base..base + 5] {
+            let mut out_column = vec![column[0].clone()];
+            out_column.extend(column[1 + lo..1 + hi].iter().cloned());
+            out_columns.push(out_column);
+        }
+    }
+
+    write_columns(&out_columns, output_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_arg_accepts_raw_seconds() {
+        assert_eq!(parse_time_arg("1700000000"), 1700000000.0);
+        assert_eq!(parse_time_arg("12.5"), 12.5);
+    }
+
+    #[test]
+    fn parse_time_arg_accepts_rfc3339() {
+        // 2023-11-14T22:13:20Z is exactly 1700000000 seconds since the epoch.
+        assert_eq!(parse_time_arg("2023-11-14T22:13:20Z"), 1700000000.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_time_arg_rejects_garbage() {
+        parse_time_arg("not a timestamp");
+    }
+
+    #[test]
+    fn bounds_finds_the_inclusive_window() {
+        let times = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(bounds(&times, 1.0, 3.0), (1, 4));
+    }
+
+    #[test]
+    fn bounds_excludes_times_before_start_and_after_end() {
+        let times = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(bounds(&times, 1.5, 3.5), (2, 4));
+    }
+
+    #[test]
+    fn bounds_is_empty_when_window_misses_everything() {
+        let times = [0.0, 1.0, 2.0];
+        assert_eq!(bounds(&times, 10.0, 20.0), (3, 3));
+    }
+}
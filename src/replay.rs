@@ -0,0 +1,50 @@
+use crate::capture::CaptureReader;
+use crate::device::read_device_map_from_stdin;
+use crate::sensor::parse_sensor_batch;
+use crate::writer::spawn_writer;
+use crate::{FLUSH_EVERY, FLUSH_EVERY_ROWS};
+
+/// Re-read a capture file written by the server's `--capture` tee and feed
+/// every record through `parse_sensor_batch`/the same CSV writer as if it had
+/// just arrived over the socket. Lets a parsing or aggregation bug be
+/// reproduced offline and `aggregate` re-run deterministically.
+pub fn replay(path: &str) {
+    let mut reader = CaptureReader::open(path).expect("Failed to open capture file");
+    let device_map = read_device_map_from_stdin();
+
+    let writer = spawn_writer(
+        "output.csv".to_string(),
+        device_map,
+        false,
+        FLUSH_EVERY_ROWS,
+        FLUSH_EVERY,
+        None,
+    );
+
+    let mut replayed = 0;
+    let mut skipped = 0;
+    while let Some((recv_time, data)) = reader
+        .next_record()
+        .expect("Failed to read capture record")
+    {
+        match parse_sensor_batch(&data) {
+            Ok(mut batch) => {
+                // supply the recorded receive time rather than re-stamping
+                // with the current time, so replay reproduces the original run
+                batch.recv_time = recv_time;
+                replayed += 1;
+                writer.send_batch(batch);
+            }
+            Err(e) => {
+                skipped += 1;
+                eprintln!("Skipping malformed record ({} total so far): {}", skipped, e);
+            }
+        }
+    }
+    writer.shutdown();
+
+    println!(
+        "Replayed {} batches, skipped {} malformed records.",
+        replayed, skipped
+    );
+}
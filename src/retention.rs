@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+use crate::sensor::SensorBatch;
+
+/// Bounds how much history `RetentionBuffer` keeps in memory. Rows are
+/// always handed to the streaming writer's output stream before they enter
+/// the buffer, so eviction only affects RAM, never the saved file. A row
+/// reaches disk at the writer's next periodic flush, which can come after
+/// the row has already been evicted here.
+#[derive(Clone, Copy, Debug)]
+pub enum RetentionPolicy {
+    Samples(usize),
+    Seconds(f64),
+}
+
+/// Ring buffer over the most recently seen batches, mirroring the capped
+/// time-series history `bottom`'s data collector keeps so memory stays flat
+/// on a long-running server.
+pub struct RetentionBuffer {
+    policy: RetentionPolicy,
+    batches: VecDeque<SensorBatch>,
+}
+
+impl RetentionBuffer {
+    pub fn new(policy: RetentionPolicy) -> Self {
+        Self {
+            policy,
+            batches: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, batch: SensorBatch) {
+        self.batches.push_back(batch);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        match self.policy {
+            RetentionPolicy::Samples(max_samples) => {
+                while self.batches.len() > max_samples {
+                    self.batches.pop_front();
+                }
+            }
+            RetentionPolicy::Seconds(window) => {
+                let Some(latest) = self.batches.back().map(|b| b.timestamp) else {
+                    return;
+                };
+                while let Some(oldest) = self.batches.front() {
+                    if latest - oldest.timestamp > window {
+                        self.batches.pop_front();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SensorBatch> {
+        self.batches.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(timestamp: f64) -> SensorBatch {
+        SensorBatch {
+            sensors: Vec::new(),
+            timestamp,
+            recv_time: timestamp,
+        }
+    }
+
+    #[test]
+    fn samples_policy_evicts_oldest_past_the_cap() {
+        let mut buffer = RetentionBuffer::new(RetentionPolicy::Samples(3));
+        for t in 0..5 {
+            buffer.push(batch(t as f64));
+        }
+
+        assert_eq!(buffer.len(), 3);
+        let timestamps: Vec<f64> = buffer.iter().map(|b| b.timestamp).collect();
+        assert_eq!(timestamps, vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn seconds_policy_evicts_anything_older_than_the_window() {
+        let mut buffer = RetentionBuffer::new(RetentionPolicy::Seconds(2.0));
+        for t in [0.0, 1.0, 2.0, 3.0, 5.0] {
+            buffer.push(batch(t));
+        }
+
+        // latest is 5.0, so only timestamps within [5.0 - 2.0, 5.0] survive
+        let timestamps: Vec<f64> = buffer.iter().map(|b| b.timestamp).collect();
+        assert_eq!(timestamps, vec![3.0, 5.0]);
+    }
+
+    #[test]
+    fn empty_buffer_never_panics_on_eviction() {
+        let mut buffer = RetentionBuffer::new(RetentionPolicy::Samples(0));
+        buffer.push(batch(1.0));
+        assert_eq!(buffer.len(), 0);
+    }
+}
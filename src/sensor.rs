@@ -0,0 +1,213 @@
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    net::UdpSocket,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+
+/// Source of raw datagrams for `get_next_data`/`recv_raw`. Implemented for
+/// `UdpSocket` so the live server path is unchanged; tests can supply their
+/// own source to exercise the same parsing code without binding a real
+/// socket. `replay` does not go through this trait: it calls
+/// `parse_sensor_batch` directly on the recorded datagrams.
+pub trait DatagramSource {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl DatagramSource for UdpSocket {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        UdpSocket::recv(self, buf)
+    }
+}
+
+#[derive(Debug)]
+pub struct SensorBatch {
+    pub sensors: Vec<SensorData>,
+    pub timestamp: f64,
+    /// Server-side receive time, stamped separately from the device's own
+    /// `timestamp` so downstream `aggregate` can diagnose lag or drift.
+    pub recv_time: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SensorData {
+    pub sensor: u8,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// Why a datagram couldn't be turned into a `SensorBatch`. Returned instead
+/// of silently dropping the packet, so callers can count and log failures.
+#[derive(Debug)]
+pub enum ParseError {
+    Empty,
+    Malformed(csv::Error),
+    BadTimestamp(String),
+    WrongFieldCount(usize),
+    BadSensor(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "datagram was empty"),
+            ParseError::Malformed(e) => write!(f, "malformed CSV record: {}", e),
+            ParseError::BadTimestamp(s) => write!(f, "could not parse timestamp {:?}", s),
+            ParseError::WrongFieldCount(n) => {
+                write!(f, "expected a multiple of 4 sensor fields, got {}", n)
+            }
+            ParseError::BadSensor(i) => write!(f, "could not parse sensor #{} in batch", i),
+        }
+    }
+}
+
+pub fn parse_sensordata(parts: &[&str]) -> Option<SensorData> {
+    Some(SensorData {
+        sensor: str::parse(parts[0].trim()).ok()?,
+        x: str::parse(parts[1].trim()).ok()?,
+        y: str::parse(parts[2].trim()).ok()?,
+        z: str::parse(parts[3].trim()).ok()?,
+    })
+}
+
+/// Parse a raw datagram (`<timestamp>,<sensor>,<x>,<y>,<z>,<sensor>,...`)
+/// using the `csv` crate's record reader rather than a bare `split(',')`, so
+/// quoted fields are handled correctly instead of breaking on them.
+pub fn parse_sensor_batch(total: &[u8]) -> Result<SensorBatch, ParseError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(total);
+
+    let record = match reader.records().next() {
+        Some(Ok(record)) => record,
+        Some(Err(e)) => return Err(ParseError::Malformed(e)),
+        None => return Err(ParseError::Empty),
+    };
+
+    let mut fields = record.iter();
+    let time_str = fields.next().ok_or(ParseError::Empty)?;
+    let timestamp: f64 = time_str
+        .trim()
+        .parse()
+        .map_err(|_| ParseError::BadTimestamp(time_str.to_string()))?;
+
+    let remaining: Vec<&str> = fields.collect();
+    if !remaining.len().is_multiple_of(4) {
+        return Err(ParseError::WrongFieldCount(remaining.len()));
+    }
+
+    let mut sensors = Vec::with_capacity(remaining.len() / 4);
+    for (sensor_i, chunk) in remaining.chunks(4).enumerate() {
+        sensors.push(parse_sensordata(chunk).ok_or(ParseError::BadSensor(sensor_i))?);
+    }
+
+    Ok(SensorBatch {
+        sensors,
+        timestamp,
+        recv_time: 0.0,
+    })
+}
+
+/// Read one raw datagram off `socket`, with no parsing applied. Exposed
+/// separately from `get_next_data` so callers that need the raw bytes (e.g.
+/// the capture tee) don't have to re-implement the recv/error-handling dance.
+pub fn recv_raw<S: DatagramSource>(socket: &S) -> io::Result<Vec<u8>> {
+    let mut buf = [0; 4096];
+    let len = match socket.recv(&mut buf) {
+        Ok(v) => v,
+        Err(e) => {
+            if e.kind() == ErrorKind::Interrupted {
+                println!("Socket read was interrupted. This is probably ok.");
+                return Err(e);
+            }
+            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                // Expected while polling a socket with a read timeout set;
+                // callers decide whether to retry or give up.
+                return Err(e);
+            }
+            eprintln!("Uncaught error: {:?}, {}", e, e);
+            return Err(e);
+        }
+    };
+
+    Ok(buf[..len].to_vec())
+}
+
+/// One received-and-stamped datagram: the raw bytes (needed by the capture
+/// tee, which records a packet whether or not it goes on to parse), the
+/// server receive time, and the parse outcome.
+pub struct Received {
+    pub raw: Vec<u8>,
+    pub recv_time: f64,
+    pub parsed: Result<SensorBatch, ParseError>,
+}
+
+/// Receive, stamp, and parse one datagram. This is the sequence the live
+/// server loop runs on every iteration; bundled into one function so the
+/// recv/stamp/parse steps can't drift out of sync with each other.
+pub fn get_next_data<S: DatagramSource, C: Clock>(socket: &S, clock: &C) -> io::Result<Received> {
+    let raw = recv_raw(socket)?;
+    let recv_time = clock.now();
+    let parsed = parse_sensor_batch(&raw).map(|mut batch| {
+        batch.recv_time = recv_time;
+        batch
+    });
+    Ok(Received {
+        raw,
+        recv_time,
+        parsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    /// A `DatagramSource` that hands back one fixed datagram, so
+    /// `get_next_data` can be exercised without binding a real `UdpSocket`.
+    struct FixedSource(Vec<u8>);
+
+    impl DatagramSource for FixedSource {
+        fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.0.len();
+            buf[..len].copy_from_slice(&self.0);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn get_next_data_stamps_recv_time_from_injected_clock() {
+        let source = FixedSource(b"1.5,1,0.1,0.2,0.3".to_vec());
+        let clock = FakeClock::new(42.0);
+
+        let received = get_next_data(&source, &clock).unwrap();
+        let batch = received.parsed.unwrap();
+
+        assert_eq!(received.recv_time, 42.0);
+        assert_eq!(received.raw, b"1.5,1,0.1,0.2,0.3");
+        assert_eq!(batch.timestamp, 1.5);
+        assert_eq!(batch.recv_time, 42.0);
+        assert_eq!(batch.sensors.len(), 1);
+        assert_eq!(batch.sensors[0].sensor, 1);
+    }
+
+    #[test]
+    fn get_next_data_still_stamps_recv_time_on_malformed_datagram() {
+        let source = FixedSource(b"not a number,1,0.1,0.2,0.3".to_vec());
+        let clock = FakeClock::new(7.0);
+
+        let received = get_next_data(&source, &clock).unwrap();
+
+        assert_eq!(received.recv_time, 7.0);
+        assert!(matches!(
+            received.parsed,
+            Err(ParseError::BadTimestamp(_))
+        ));
+    }
+}
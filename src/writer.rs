@@ -0,0 +1,232 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use csv::Writer;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::device::DeviceMap;
+use crate::retention::{RetentionBuffer, RetentionPolicy};
+use crate::sensor::SensorBatch;
+
+enum WriterMsg {
+    Row(SensorBatch),
+    Shutdown,
+}
+
+/// Output stream for the CSV writer, plain or gzip-compressed. Kept as its
+/// own enum (rather than `Box<dyn Write>`) because gzip needs a consuming
+/// `finish()` call to write its footer, which a plain file doesn't have and
+/// `Write::flush` alone doesn't trigger.
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            Sink::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+impl Sink {
+    /// Finalize the stream: for gzip this writes the footer that a bare
+    /// `flush()` never would, for a plain file it's just a last flush.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut w) => w.flush(),
+            Sink::Gzip(w) => w.finish().map(|_| ()),
+        }
+    }
+}
+
+/// Number of batches and device-timestamp coverage of the retention window
+/// at the moment it was read. Returned by `shutdown` rather than read
+/// mid-run, so it reflects every row Ctrl-C actually managed to write
+/// instead of whatever had drained off the channel when it was queried.
+pub struct RetentionSummary {
+    pub batches: usize,
+    pub time_range: Option<(f64, f64)>,
+}
+
+/// Handle to the background CSV writer thread. Rows are sent over an `mpsc`
+/// channel instead of being accumulated in memory, so a long-running server
+/// only ever holds a handful of pending rows at a time.
+pub struct WriterHandle {
+    tx: mpsc::Sender<WriterMsg>,
+    thread: Option<JoinHandle<()>>,
+    retention: Option<Arc<Mutex<RetentionBuffer>>>,
+}
+
+impl WriterHandle {
+    pub fn send_batch(&self, batch: SensorBatch) {
+        // the writer thread outlives every sender in normal operation; if it's
+        // already gone there's nothing useful left to do with this batch.
+        let _ = self.tx.send(WriterMsg::Row(batch));
+    }
+
+    /// Ask the writer thread to flush and close the file, wait for it to
+    /// finish draining the channel, and only then report what ended up in
+    /// the retention window. Querying the buffer before the join could miss
+    /// rows still sitting in the channel ahead of the `Shutdown` message.
+    pub fn shutdown(mut self) -> Option<RetentionSummary> {
+        let _ = self.tx.send(WriterMsg::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let retention = self.retention.as_ref()?;
+        let buffer = retention.lock().unwrap();
+        let batches = buffer.len();
+        let mut iter = buffer.iter();
+        let time_range = iter.next().map(|first| {
+            let last = iter.last().map_or(first.timestamp, |b| b.timestamp);
+            (first.timestamp, last)
+        });
+        Some(RetentionSummary {
+            batches,
+            time_range,
+        })
+    }
+}
+
+/// Spawn the writer thread. `flush_every_rows` and `flush_every` bound how
+/// long unflushed data can sit in the in-process `BufWriter` before it's
+/// handed to the OS; whichever limit is hit first triggers a flush.
+/// `retention`, when given, keeps a bounded in-memory window of recent
+/// batches (each already written to the output stream before it enters the
+/// window, though not necessarily flushed before it is evicted) instead of
+/// none at all.
+pub fn spawn_writer(
+    path: String,
+    device_map: DeviceMap,
+    gzip: bool,
+    flush_every_rows: usize,
+    flush_every: Duration,
+    retention: Option<RetentionPolicy>,
+) -> WriterHandle {
+    let (tx, rx) = mpsc::channel();
+    let retention_buffer = retention.map(|policy| Arc::new(Mutex::new(RetentionBuffer::new(policy))));
+    let thread_retention_buffer = retention_buffer.clone();
+
+    let thread = thread::spawn(move || {
+        let mut devices: Vec<(u8, String, usize)> = device_map
+            .into_iter()
+            .map(|(num, (name, column))| (num, name, column))
+            .collect();
+        devices.sort_by_key(|(_, _, column)| *column);
+
+        let file = File::create(&path).expect("Failed to create output file");
+        let sink = if gzip {
+            Sink::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Sink::Plain(BufWriter::new(file))
+        };
+        let mut out = Writer::from_writer(sink);
+
+        write_header(&mut out, &devices);
+
+        let mut rows_since_flush = 0;
+        let mut last_flush = Instant::now();
+        loop {
+            match rx.recv_timeout(flush_every) {
+                Ok(WriterMsg::Row(batch)) => {
+                    write_row(&mut out, &devices, &batch);
+                    rows_since_flush += 1;
+                    if rows_since_flush >= flush_every_rows || last_flush.elapsed() >= flush_every
+                    {
+                        out.flush().expect("Failed to flush output file");
+                        rows_since_flush = 0;
+                        last_flush = Instant::now();
+                    }
+                    // the row is already written to the output stream above
+                    // (on disk by the next flush), so evicting an older batch
+                    // from the retention window never drops it from the file
+                    if let Some(retention_buffer) = &thread_retention_buffer {
+                        retention_buffer.lock().unwrap().push(batch);
+                    }
+                }
+                Ok(WriterMsg::Shutdown) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if rows_since_flush > 0 {
+                        out.flush().expect("Failed to flush output file");
+                        rows_since_flush = 0;
+                        last_flush = Instant::now();
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        out.flush().expect("Failed to flush output file on shutdown");
+        out.into_inner()
+            .expect("Failed to release output file")
+            .finish()
+            .expect("Failed to finalize output file");
+    });
+
+    WriterHandle {
+        tx,
+        thread: Some(thread),
+        retention: retention_buffer,
+    }
+}
+
+/// Each device keeps its own 5-cell block (Time, X, Y, Z, server receive
+/// time) so that a device's time column only ever contains timestamps from
+/// batches it actually appeared in, matching the ragged-per-device layout
+/// `aggregate` expects. The receive-time column lets downstream analysis
+/// compare it against the device's own `Time (s)` column to spot clock drift
+/// or dropped packets. Going through `csv::Writer` rather than manual string
+/// formatting means a device name containing a comma gets quoted correctly
+/// instead of silently corrupting the column layout.
+fn write_header(out: &mut Writer<Sink>, devices: &[(u8, String, usize)]) {
+    let mut record = Vec::with_capacity(devices.len() * 5);
+    for (num, name, _) in devices {
+        record.push("Time (s)".to_string());
+        record.push(format!("{}: X ({})", name, num));
+        record.push(format!("{}: Y ({})", name, num));
+        record.push(format!("{}: Z ({})", name, num));
+        record.push(format!("{}: Recv Time (s) ({})", name, num));
+    }
+    out.write_record(&record).expect("Failed to write header row");
+}
+
+/// Write one row per incoming batch: the reporting devices get their
+/// timestamp/x/y/z/receive-time filled in, everyone else gets blank cells
+/// for that row (the same ragged-column convention `aggregate` already
+/// tolerates).
+fn write_row(out: &mut Writer<Sink>, devices: &[(u8, String, usize)], batch: &SensorBatch) {
+    let mut record = Vec::with_capacity(devices.len() * 5);
+    for (num, _, _) in devices {
+        match batch.sensors.iter().find(|s| s.sensor == *num) {
+            Some(data) => {
+                record.push(batch.timestamp.to_string());
+                record.push(data.x.to_string());
+                record.push(data.y.to_string());
+                record.push(data.z.to_string());
+                record.push(batch.recv_time.to_string());
+            }
+            None => {
+                for _ in 0..5 {
+                    record.push(String::new());
+                }
+            }
+        }
+    }
+    out.write_record(&record).expect("Failed to write data row");
+}